@@ -1,7 +1,7 @@
 use std::io::Write;
 
 use anyhow::Result;
-use glam::{Mat2, Vec2};
+use glam::{Mat2, Vec2, Vec3};
 use image::io::Reader as ImageReader;
 use image::{Pixel, Rgba, RgbaImage};
 
@@ -15,6 +15,48 @@ const TEXTURE_HEIGHT: usize = TEXTURE_SRC_SIZE as usize * SIDES as usize;
 const ISOMETRIC_WIDTH: usize = (TEXTURE_SRC_SIZE * 2) as usize;
 const ISOMETRIC_HEIGHT: usize = (TEXTURE_SRC_SIZE * 2) as usize;
 
+const DEFAULT_SUPERSAMPLE_FACTOR: u8 = 4;
+const DEFAULT_AMBIENT: f32 = 0.3;
+
+// World-space normals of the three visible cube faces, before lighting.
+const FACE_NORMAL_TOP: Vec3 = Vec3::new(0.0, 0.0, 1.0);
+const FACE_NORMAL_LEFT: Vec3 = Vec3::new(-1.0, 0.0, 0.0);
+const FACE_NORMAL_RIGHT: Vec3 = Vec3::new(0.0, -1.0, 0.0);
+
+/// Reconstruction filter used to weight subpixel samples when supersampling.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReconstructionFilter {
+    Box,
+    Triangle,
+    /// Gaussian with a fixed sigma of 0.5 pixel, independent of the supersample factor.
+    Gaussian,
+}
+
+impl ReconstructionFilter {
+    fn weight(&self, dx: f32, dy: f32) -> f32 {
+        match self {
+            ReconstructionFilter::Box => 1.0,
+            ReconstructionFilter::Triangle => {
+                (1.0 - 2.0 * dx.abs()).max(0.0) * (1.0 - 2.0 * dy.abs()).max(0.0)
+            }
+            ReconstructionFilter::Gaussian => {
+                const SIGMA: f32 = 0.5;
+                (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+            }
+        }
+    }
+}
+
+/// How a sampled face is composited onto the faces already written to a pixel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendMode {
+    Src,
+    SrcOver,
+    Darken,
+    Lighten,
+    Multiply,
+}
+
 pub struct TextureConverter {
     texture_map_path: String,
     img: RgbaImage,
@@ -22,6 +64,22 @@ pub struct TextureConverter {
     palette: Vec<Rgba<u8>>,
     unmodified_texture: Vec<Vec<Rgba<u8>>>,
     isometric_texture: Vec<Vec<Rgba<u8>>>,
+
+    // Supersampled anti-aliasing of the isometric projection.
+    supersample_factor: u8,
+    reconstruction_filter: ReconstructionFilter,
+    blend_mode: BlendMode,
+
+    // Mip chain per isometric block: (width, height, pixels), coarsest level last.
+    isometric_mips: Vec<Vec<(usize, usize, Vec<Rgba<u8>>)>>,
+
+    // Directional lighting of the cube faces.
+    light_direction: Vec3,
+    ambient: f32,
+
+    // Per-block RGB-encoded surface normals, registered pixel-for-pixel with
+    // `isometric_texture`.
+    isometric_normals: Vec<Vec<Rgba<u8>>>,
 }
 
 impl TextureConverter {
@@ -44,21 +102,110 @@ impl TextureConverter {
             palette,
             unmodified_texture,
             isometric_texture,
+            supersample_factor: DEFAULT_SUPERSAMPLE_FACTOR,
+            reconstruction_filter: ReconstructionFilter::Gaussian,
+            blend_mode: BlendMode::SrcOver,
+            isometric_mips: Vec::new(),
+            light_direction: Vec3::new(0.4, -0.4, 0.8).normalize(),
+            ambient: DEFAULT_AMBIENT,
+            isometric_normals: Vec::new(),
         })
     }
 
+    pub fn set_light_direction(&mut self, light_direction: Vec3) {
+        self.light_direction = light_direction.normalize();
+    }
+
+    pub fn set_ambient(&mut self, ambient: f32) {
+        self.ambient = ambient.clamp(0.0, 1.0);
+    }
+
+    pub fn set_supersample_factor(&mut self, factor: u8) {
+        self.supersample_factor = factor.max(1);
+    }
+
+    pub fn set_reconstruction_filter(&mut self, filter: ReconstructionFilter) {
+        self.reconstruction_filter = filter;
+    }
+
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+    }
+
     pub fn generate_rust_texture_source(&mut self) {
         let (width, height) = self.img.dimensions();
         self.normalize_transparent_pixels();
         self.fill_palette();
         self.set_unmodified_texture_source();
         self.set_isometric_texture_source();
+        self.generate_isometric_mipmaps();
+        self.generate_isometric_normals();
 
         self.debug_draw();
 
         self.save_textures("./src/textures.rs");
     }
 
+    // Alternative to `save_textures`: packs the isometric blocks into one atlas
+    // PNG plus a Rust table of UV rects, instead of a giant const array.
+    pub fn generate_texture_atlas(&self, image_path: &str, table_path: &str) {
+        // `set_isometric_texture_source` only fills the first `BLOCKS` entries;
+        // the rest are still the 32*31 construction-time placeholders and are
+        // too short to index as a full ISOMETRIC_WIDTH*ISOMETRIC_HEIGHT tile.
+        let populated = &self.isometric_texture[..usize::from(BLOCKS)];
+        let sizes: Vec<(u32, u32)> = std::iter::repeat((ISOMETRIC_WIDTH as u32, ISOMETRIC_HEIGHT as u32))
+            .take(populated.len())
+            .collect();
+        let atlas_width = ISOMETRIC_WIDTH as u32 * (sizes.len() as f32).sqrt().ceil() as u32;
+        let (atlas_height, rects) = Self::pack_shelves(&sizes, atlas_width);
+
+        let mut atlas = RgbaImage::new(atlas_width, atlas_height.max(1));
+        for (texture, &(x, y, w, h)) in populated.iter().zip(&rects) {
+            for row in 0..h {
+                for col in 0..w {
+                    atlas.put_pixel(x + col, y + row, texture[(col + row * w) as usize]);
+                }
+            }
+        }
+        atlas.save(image_path).unwrap();
+
+        let mut contents = format!(
+            "pub const ATLAS_RECTS: [(u32, u32, u32, u32); {}] = [\n",
+            rects.len()
+        );
+        for (x, y, w, h) in &rects {
+            contents = format!("{contents}({x}, {y}, {w}, {h}),\n");
+        }
+        contents = format!("{contents}];");
+        std::fs::write(table_path, contents).unwrap();
+    }
+
+    // Shelf packer: tiles are sorted tallest-first and placed left-to-right on
+    // the current shelf, opening a new shelf (tracked by its max tile height)
+    // once a row would overflow `atlas_width`. This only tracks one height per
+    // shelf, not a per-column skyline, so it wastes space on non-uniform tiles;
+    // harmless here since every isometric block is the same size.
+    fn pack_shelves(sizes: &[(u32, u32)], atlas_width: u32) -> (u32, Vec<(u32, u32, u32, u32)>) {
+        let mut order: Vec<usize> = (0..sizes.len()).collect();
+        order.sort_by(|&a, &b| sizes[b].1.cmp(&sizes[a].1));
+
+        let mut rects = vec![(0, 0, 0, 0); sizes.len()];
+        let (mut shelf_x, mut shelf_y, mut shelf_h, mut atlas_height) = (0u32, 0u32, 0u32, 0u32);
+        for idx in order {
+            let (w, h) = sizes[idx];
+            if shelf_x + w > atlas_width && shelf_x > 0 {
+                shelf_y += shelf_h;
+                shelf_x = 0;
+                shelf_h = 0;
+            }
+            rects[idx] = (shelf_x, shelf_y, w, h);
+            shelf_x += w;
+            shelf_h = shelf_h.max(h);
+            atlas_height = atlas_height.max(shelf_y + shelf_h);
+        }
+        (atlas_height, rects)
+    }
+
     fn save_textures(&self, path: &str) {
         let mut file = std::fs::File::options()
             .create(true)
@@ -83,6 +230,236 @@ impl TextureConverter {
         contents = format!("{contents}];");
         file.write_all(contents.as_bytes());
         // println!("{}", contents);
+
+        self.write_mipmaps(&mut file);
+        self.write_normals(&mut file);
+    }
+
+    fn write_normals(&self, file: &mut std::fs::File) {
+        let size = ISOMETRIC_HEIGHT * ISOMETRIC_WIDTH;
+        let mut contents = format!("\npub const NORMALS: [[[u8; 4]; {size}]; {BLOCKS}] = [\n");
+        for texture in self.isometric_normals.iter().take(usize::from(BLOCKS)) {
+            contents = format!("{contents}[\n");
+            for pixel in texture {
+                let [r, g, b, a] = pixel.channels() else {
+                    panic!();
+                };
+                contents = format!("{contents}[{r}, {g}, {b}, {a}],");
+            }
+            contents = format!("{contents}],\n");
+            file.write_all(contents.as_bytes());
+            contents.clear();
+        }
+        contents = format!("{contents}];");
+        file.write_all(contents.as_bytes());
+    }
+
+    fn write_mipmaps(&self, file: &mut std::fs::File) {
+        let levels = self.isometric_mips.first().map_or(0, Vec::len);
+
+        let mut sizes = format!("\npub const TEXTURES_MIP_SIZES: [(u32, u32); {levels}] = [\n");
+        if let Some(chain) = self.isometric_mips.first() {
+            for (w, h, _) in chain {
+                sizes = format!("{sizes}({w}, {h}),");
+            }
+        }
+        sizes = format!("{sizes}\n];\n");
+        file.write_all(sizes.as_bytes());
+
+        let mut contents =
+            format!("pub const TEXTURES_MIPS: [[&[[u8; 4]]; {levels}]; {BLOCKS}] = [\n");
+        for chain in self.isometric_mips.iter().take(usize::from(BLOCKS)) {
+            contents = format!("{contents}[\n");
+            for (_, _, pixels) in chain {
+                contents = format!("{contents}&[");
+                for pixel in pixels {
+                    let [r, g, b, a] = pixel.channels() else {
+                        panic!();
+                    };
+                    contents = format!("{contents}[{r}, {g}, {b}, {a}],");
+                }
+                contents = format!("{contents}],\n");
+            }
+            contents = format!("{contents}],\n");
+            file.write_all(contents.as_bytes());
+            contents.clear();
+        }
+        contents = format!("{contents}];");
+        file.write_all(contents.as_bytes());
+    }
+
+    // Every isometric block shares the same silhouette and face normals, so
+    // the normal-encoded tile is geometry-only and identical across blocks;
+    // compute it once and register it pixel-for-pixel with each color tile.
+    fn generate_isometric_normals(&mut self) {
+        // Only the first `BLOCKS` entries of `isometric_texture` are ever
+        // populated by `set_isometric_texture_source`; the rest are still the
+        // construction-time placeholders.
+        let normal_tile = self.transform_isometric_normal_tile();
+        self.isometric_normals = vec![normal_tile; usize::from(BLOCKS)];
+    }
+
+    // Reuses the color projection's inverse-matrix/shear mapping, but swaps
+    // the payload from sampled color to the RGB-encoded face normal.
+    fn transform_isometric_normal_tile(&self) -> Vec<Rgba<u8>> {
+        let mut out: Vec<Vec<_>> =
+            vec![vec![Rgba::from([0, 0, 0, 0]); ISOMETRIC_WIDTH]; ISOMETRIC_HEIGHT];
+
+        let top_offset = ISOMETRIC_HEIGHT / 4;
+        let transformation_matrix: Mat2 = Mat2::from_cols_array_2d(&[[1.0, -0.5], [1.0, 0.5]]);
+        let top_inverse = transformation_matrix.inverse();
+        let top_offset_vec = Vec2::new(0.0, top_offset as f32);
+        for y in 0..ISOMETRIC_HEIGHT {
+            for x in 0..ISOMETRIC_WIDTH {
+                let coverage =
+                    self.sample_face_coverage(|pos| top_inverse.mul_vec2(pos), x, y, top_offset_vec);
+                if coverage > 0.0 {
+                    let encoded = Self::encode_normal(FACE_NORMAL_TOP, coverage);
+                    out[y][x] = self.composite_pixel(out[y][x], encoded);
+                }
+            }
+        }
+        let shear = Vec2::new(-0.5, 0.0);
+        let shear_matrix = Mat2::from_cols_array_2d(&[[1.0, shear.x], [shear.y, 1.0]]);
+        for y in 0..ISOMETRIC_HEIGHT {
+            for x in 0..ISOMETRIC_WIDTH {
+                let coverage =
+                    self.sample_face_coverage(|pos| shear_matrix.mul_vec2(pos), x, y, top_offset_vec);
+                if coverage > 0.0 {
+                    let encoded = Self::encode_normal(FACE_NORMAL_LEFT, coverage);
+                    out[y][x] = self.composite_pixel(out[y][x], encoded);
+                }
+            }
+        }
+
+        let center = ISOMETRIC_HEIGHT as f32 / 2.0;
+        let shear = Vec2::new(0.5, 0.0);
+        let shear_matrix = Mat2::from_cols_array_2d(&[[1.0, shear.x], [shear.y, 1.0]]);
+        let center_vec = Vec2::new(center, center);
+        for y in 0..ISOMETRIC_HEIGHT {
+            for x in 0..ISOMETRIC_WIDTH {
+                let coverage =
+                    self.sample_face_coverage(|pos| shear_matrix.mul_vec2(pos), x, y, center_vec);
+                if coverage > 0.0 {
+                    let encoded = Self::encode_normal(FACE_NORMAL_RIGHT, coverage);
+                    out[y][x] = self.composite_pixel(out[y][x], encoded);
+                }
+            }
+        }
+        out.concat()
+    }
+
+    // Same subpixel grid and `fits_inside_rect` test as `sample_face_supersampled`,
+    // but only the geometric coverage is needed for the normal map.
+    fn sample_face_coverage(
+        &self,
+        to_source: impl Fn(Vec2) -> Vec2,
+        out_x: usize,
+        out_y: usize,
+        offset: Vec2,
+    ) -> f32 {
+        let s = self.supersample_factor.max(1);
+        let mut inside = 0u32;
+        for j in 0..s {
+            for i in 0..s {
+                let dx = (i as f32 + 0.5) / s as f32 - 0.5;
+                let dy = (j as f32 + 0.5) / s as f32 - 0.5;
+                let pos = Vec2::new(out_x as f32 + dx, out_y as f32 + dy) - offset;
+                let sample_pos = to_source(pos);
+                if fits_inside_rect(&sample_pos, TEXTURE_SRC_SIZE as f32) {
+                    inside += 1;
+                }
+            }
+        }
+        inside as f32 / (s as f32 * s as f32)
+    }
+
+    // RGB-encodes a unit normal as `(n*0.5 + 0.5)*255` per channel, alpha from coverage.
+    fn encode_normal(normal: Vec3, coverage: f32) -> Rgba<u8> {
+        let n = normal.normalize();
+        let encode = |v: f32| ((v * 0.5 + 0.5) * 255.0).round().clamp(0.0, 255.0) as u8;
+        Rgba::from([
+            encode(n.x),
+            encode(n.y),
+            encode(n.z),
+            (coverage * 255.0).round() as u8,
+        ])
+    }
+
+    // Generates a box-filtered mip chain (halving each axis) for every isometric
+    // block down to a 1x1 texel, clamping the last sampled row/column on odd
+    // dimensions so we never read past the edge.
+    fn generate_isometric_mipmaps(&mut self) {
+        // `set_isometric_texture_source` only fills the first `BLOCKS` entries;
+        // the rest are still the 32*31 construction-time placeholders, too
+        // short for a 32x32 mip chain (same hazard `generate_texture_atlas` guards against).
+        self.isometric_mips = self.isometric_texture[..usize::from(BLOCKS)]
+            .iter()
+            .map(|base| Self::build_mip_chain(base, ISOMETRIC_WIDTH, ISOMETRIC_HEIGHT))
+            .collect();
+    }
+
+    fn build_mip_chain(
+        base: &[Rgba<u8>],
+        width: usize,
+        height: usize,
+    ) -> Vec<(usize, usize, Vec<Rgba<u8>>)> {
+        let mut levels = vec![(width, height, base.to_vec())];
+        let (mut prev_w, mut prev_h) = (width, height);
+        while prev_w > 1 || prev_h > 1 {
+            let next_w = (prev_w / 2).max(1);
+            let next_h = (prev_h / 2).max(1);
+            let prev = &levels.last().unwrap().2;
+            let mut next = vec![Rgba::from([0u8, 0, 0, 0]); next_w * next_h];
+            for y in 0..next_h {
+                for x in 0..next_w {
+                    let x0 = (x * 2).min(prev_w - 1);
+                    let x1 = (x * 2 + 1).min(prev_w - 1);
+                    let y0 = (y * 2).min(prev_h - 1);
+                    let y1 = (y * 2 + 1).min(prev_h - 1);
+                    let a = prev[x0 + y0 * prev_w];
+                    let b = prev[x1 + y0 * prev_w];
+                    let c = prev[x0 + y1 * prev_w];
+                    let d = prev[x1 + y1 * prev_w];
+                    next[x + y * next_w] = Self::box_average_premultiplied(a, b, c, d);
+                }
+            }
+            levels.push((next_w, next_h, next));
+            prev_w = next_w;
+            prev_h = next_h;
+        }
+        levels
+    }
+
+    // dst[c] = (a[c] + b[c] + c[c] + d[c]) >> 2 per channel, carried out in
+    // premultiplied alpha so transparent edges don't leak color into the average.
+    fn box_average_premultiplied(a: Rgba<u8>, b: Rgba<u8>, c: Rgba<u8>, d: Rgba<u8>) -> Rgba<u8> {
+        let premultiply = |p: Rgba<u8>| -> [u32; 4] {
+            let ch = p.channels();
+            let alpha = ch[3] as u32;
+            [
+                ch[0] as u32 * alpha / 255,
+                ch[1] as u32 * alpha / 255,
+                ch[2] as u32 * alpha / 255,
+                alpha,
+            ]
+        };
+        let (pa, pb, pc, pd) = (premultiply(a), premultiply(b), premultiply(c), premultiply(d));
+        let mut avg = [0u32; 4];
+        for (channel, avg_channel) in avg.iter_mut().enumerate() {
+            *avg_channel = (pa[channel] + pb[channel] + pc[channel] + pd[channel]) >> 2;
+        }
+        let alpha = avg[3];
+        if alpha == 0 {
+            return Rgba::from([0, 0, 0, 0]);
+        }
+        let unpremultiply = |v: u32| (v * 255 / alpha).min(255) as u8;
+        Rgba::from([
+            unpremultiply(avg[0]),
+            unpremultiply(avg[1]),
+            unpremultiply(avg[2]),
+            alpha as u8,
+        ])
     }
 
     fn set_unmodified_texture_source(&mut self) {
@@ -134,32 +511,44 @@ impl TextureConverter {
             vec![vec![Rgba::from([0, 0, 0, 0]); ISOMETRIC_WIDTH]; ISOMETRIC_HEIGHT];
 
         let top_offset = ISOMETRIC_HEIGHT / 4;
+        let top_light = self.lambert_term(FACE_NORMAL_TOP);
+        let left_light = self.lambert_term(FACE_NORMAL_LEFT);
+        let right_light = self.lambert_term(FACE_NORMAL_RIGHT);
+
         // top side, transformation matrix, y offset is top_offset
         let transformation_matrix: Mat2 = Mat2::from_cols_array_2d(&[[1.0, -0.5], [1.0, 0.5]]);
-        for y in 0..=ISOMETRIC_HEIGHT {
-            for x in 0..=ISOMETRIC_WIDTH {
-                let pos = Vec2::new(x as f32, y as f32 - top_offset as f32);
-                let sample_pos = transformation_matrix.inverse().mul_vec2(pos);
-                if fits_inside_rect(&sample_pos, TEXTURE_SRC_SIZE as f32) {
-                    let sx = sample_pos[0].floor();
-                    let sy = sample_pos[1].floor();
-                    let idx = sx as usize + sy as usize * usize::from(TEXTURE_SRC_SIZE);
-                    out[y][x] = top[idx];
+        let top_inverse = transformation_matrix.inverse();
+        let top_offset_vec = Vec2::new(0.0, top_offset as f32);
+        for y in 0..ISOMETRIC_HEIGHT {
+            for x in 0..ISOMETRIC_WIDTH {
+                let sampled = self.sample_face_supersampled(
+                    top,
+                    |pos| top_inverse.mul_vec2(pos),
+                    x,
+                    y,
+                    top_offset_vec,
+                );
+                if sampled.channels()[3] > 0 {
+                    let lit = Self::shade(sampled, top_light);
+                    out[y][x] = self.composite_pixel(out[y][x], lit);
                 }
             }
         }
         // left, shear matrix, y offset is top_offset
         let shear = Vec2::new(-0.5, 0.0);
         let shear_matrix = Mat2::from_cols_array_2d(&[[1.0, shear.x], [shear.y, 1.0]]);
-        for y in 0..=ISOMETRIC_HEIGHT {
-            for x in 0..=ISOMETRIC_WIDTH {
-                let pos = Vec2::new(x as f32, y as f32 - top_offset as f32);
-                let sample_pos = shear_matrix.mul_vec2(pos);
-                if fits_inside_rect(&sample_pos, TEXTURE_SRC_SIZE as f32) {
-                    let sx = sample_pos[0].floor();
-                    let sy = sample_pos[1].floor();
-                    let idx = sx as usize + sy as usize * usize::from(TEXTURE_SRC_SIZE);
-                    out[y][x] = left[idx];
+        for y in 0..ISOMETRIC_HEIGHT {
+            for x in 0..ISOMETRIC_WIDTH {
+                let sampled = self.sample_face_supersampled(
+                    left,
+                    |pos| shear_matrix.mul_vec2(pos),
+                    x,
+                    y,
+                    top_offset_vec,
+                );
+                if sampled.channels()[3] > 0 {
+                    let lit = Self::shade(sampled, left_light);
+                    out[y][x] = self.composite_pixel(out[y][x], lit);
                 }
             }
         }
@@ -168,21 +557,153 @@ impl TextureConverter {
         let center = ISOMETRIC_HEIGHT as f32 / 2.0;
         let shear = Vec2::new(0.5, 0.0);
         let shear_matrix = Mat2::from_cols_array_2d(&[[1.0, shear.x], [shear.y, 1.0]]);
-        for y in 0..=ISOMETRIC_HEIGHT {
-            for x in 0..=ISOMETRIC_WIDTH {
-                let pos = Vec2::new(x as f32 - center, y as f32 - center);
-                let sample_pos = shear_matrix.mul_vec2(pos);
-                if fits_inside_rect(&sample_pos, TEXTURE_SRC_SIZE as f32) {
-                    let sx = sample_pos[0].floor();
-                    let sy = sample_pos[1].floor();
-                    let idx = sx as usize + sy as usize * usize::from(TEXTURE_SRC_SIZE);
-                    out[y][x] = right[idx];
+        let center_vec = Vec2::new(center, center);
+        for y in 0..ISOMETRIC_HEIGHT {
+            for x in 0..ISOMETRIC_WIDTH {
+                let sampled = self.sample_face_supersampled(
+                    right,
+                    |pos| shear_matrix.mul_vec2(pos),
+                    x,
+                    y,
+                    center_vec,
+                );
+                if sampled.channels()[3] > 0 {
+                    let lit = Self::shade(sampled, right_light);
+                    out[y][x] = self.composite_pixel(out[y][x], lit);
                 }
             }
         }
         out.concat()
     }
 
+    // Lambert term for a face normal against the configured light, floored at
+    // the configured ambient so faces facing away from the light aren't black.
+    fn lambert_term(&self, normal: Vec3) -> f32 {
+        normal
+            .normalize()
+            .dot(self.light_direction)
+            .max(self.ambient)
+    }
+
+    // Scales a sampled pixel's RGB by a precomputed per-face lighting factor,
+    // leaving alpha untouched.
+    fn shade(pixel: Rgba<u8>, factor: f32) -> Rgba<u8> {
+        let c = pixel.channels();
+        let scale = |v: u8| ((v as f32 * factor).clamp(0.0, 255.0)).round() as u8;
+        Rgba::from([scale(c[0]), scale(c[1]), scale(c[2]), c[3]])
+    }
+
+    // Composites `src` over `dst` in premultiplied-alpha space using the
+    // configured blend mode, then un-premultiplies for storage.
+    fn composite_pixel(&self, dst: Rgba<u8>, src: Rgba<u8>) -> Rgba<u8> {
+        let premultiply = |p: Rgba<u8>| -> [f32; 4] {
+            let c = p.channels();
+            let a = c[3] as f32 / 255.0;
+            [
+                c[0] as f32 / 255.0 * a,
+                c[1] as f32 / 255.0 * a,
+                c[2] as f32 / 255.0 * a,
+                a,
+            ]
+        };
+        let d = premultiply(dst);
+        let s = premultiply(src);
+        let out_a = s[3] + d[3] * (1.0 - s[3]);
+        // An empty destination (nothing written there yet) has no color to darken,
+        // lighten, or multiply against, so the first paint into a pixel is always Src.
+        let out_rgb = if d[3] <= 0.0 {
+            [s[0], s[1], s[2]]
+        } else {
+            match self.blend_mode {
+                BlendMode::Src => [s[0], s[1], s[2]],
+                BlendMode::SrcOver => [
+                    s[0] + d[0] * (1.0 - s[3]),
+                    s[1] + d[1] * (1.0 - s[3]),
+                    s[2] + d[2] * (1.0 - s[3]),
+                ],
+                BlendMode::Darken => [s[0].min(d[0]), s[1].min(d[1]), s[2].min(d[2])],
+                BlendMode::Lighten => [s[0].max(d[0]), s[1].max(d[1]), s[2].max(d[2])],
+                BlendMode::Multiply => [s[0] * d[0], s[1] * d[1], s[2] * d[2]],
+            }
+        };
+        if out_a <= 0.0 {
+            return Rgba::from([0, 0, 0, 0]);
+        }
+        let unpremultiply = |v: f32| ((v / out_a).clamp(0.0, 1.0) * 255.0).round() as u8;
+        Rgba::from([
+            unpremultiply(out_rgb[0]),
+            unpremultiply(out_rgb[1]),
+            unpremultiply(out_rgb[2]),
+            (out_a.clamp(0.0, 1.0) * 255.0).round() as u8,
+        ])
+    }
+
+    // Samples `face` at output pixel (out_x, out_y) with an SxS grid of subpixel
+    // offsets mapped through `to_source`, accumulating in premultiplied color so
+    // transparent/magenta source texels don't bleed into the covered ones. Alpha
+    // is the fractional geometric coverage times the filter-weighted average
+    // alpha of the covered texels.
+    fn sample_face_supersampled(
+        &self,
+        face: &[Rgba<u8>],
+        to_source: impl Fn(Vec2) -> Vec2,
+        out_x: usize,
+        out_y: usize,
+        offset: Vec2,
+    ) -> Rgba<u8> {
+        let s = self.supersample_factor.max(1);
+        let mut premultiplied = [0f32; 3];
+        let mut weight_sum = 0f32;
+        let mut alpha_sum = 0f32;
+        let mut inside = 0u32;
+        for j in 0..s {
+            for i in 0..s {
+                let dx = (i as f32 + 0.5) / s as f32 - 0.5;
+                let dy = (j as f32 + 0.5) / s as f32 - 0.5;
+                let pos = Vec2::new(out_x as f32 + dx, out_y as f32 + dy) - offset;
+                let sample_pos = to_source(pos);
+                if !fits_inside_rect(&sample_pos, TEXTURE_SRC_SIZE as f32) {
+                    continue;
+                }
+                inside += 1;
+                let sx = sample_pos.x.floor() as usize;
+                let sy = sample_pos.y.floor() as usize;
+                let texel = face[sx + sy * usize::from(TEXTURE_SRC_SIZE)];
+                let texel_alpha = texel.channels()[3] as f32 / 255.0;
+                let w = self.reconstruction_filter.weight(dx, dy);
+                for (c, channel) in premultiplied.iter_mut().enumerate() {
+                    *channel += texel.channels()[c] as f32 / 255.0 * texel_alpha * w;
+                }
+                weight_sum += w;
+                alpha_sum += texel_alpha * w;
+            }
+        }
+        let geometric_coverage = inside as f32 / (s as f32 * s as f32);
+        if geometric_coverage <= 0.0 || weight_sum <= 0.0 {
+            return Rgba::from([0, 0, 0, 0]);
+        }
+        // Un-premultiply by the weighted average alpha of the covered samples,
+        // not by the geometric coverage, or opaque-edge pixels come out too bright.
+        let avg_alpha = alpha_sum / weight_sum;
+        // Fold the source texels' own opacity into the output alpha too, or a
+        // fully-transparent texel inside the silhouette would come out opaque black.
+        let coverage = geometric_coverage * avg_alpha;
+        if coverage <= 0.0 {
+            return Rgba::from([0, 0, 0, 0]);
+        }
+        let mut straight = [0u8; 3];
+        for (c, channel) in straight.iter_mut().enumerate() {
+            let avg_premultiplied = premultiplied[c] / weight_sum;
+            *channel = ((avg_premultiplied / avg_alpha).clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+        Rgba::from([
+            straight[0],
+            straight[1],
+            straight[2],
+            (coverage.clamp(0.0, 1.0) * 255.0).round() as u8,
+        ])
+    }
+
     fn fill_palette(&mut self) {
         for pixel in self.img.pixels() {
             if !self.palette.contains(pixel) {
@@ -197,14 +718,6 @@ impl TextureConverter {
             let water = Rgba::from_slice(&slice);
             self.palette.push(*water);
         }
-        let palette = self.palette.clone();
-        // shadow
-        for pixel in palette {
-            let color = pixel.channels();
-            let slice = [color[0] / 2, color[1] / 2, color[2] / 2, color[3]];
-            let water = Rgba::from_slice(&slice);
-            self.palette.push(*water);
-        }
     }
 
     // Force transparent pixels to the same color